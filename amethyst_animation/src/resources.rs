@@ -1,3 +1,4 @@
+use std::fmt;
 use std::hash::Hash;
 use std::marker;
 use std::time::Duration;
@@ -6,6 +7,7 @@ use amethyst_assets::{Asset, AssetStorage, Handle, Result};
 use amethyst_core::timing::{duration_to_secs, secs_to_duration};
 use fnv::FnvHashMap;
 use minterpolate::{get_input_index, InterpolationFunction, InterpolationPrimitive};
+use shrev::EventChannel;
 use specs::{Component, DenseVecStorage, Entity, VecStorage};
 
 /// Master trait used to define animation sampling on a component
@@ -22,6 +24,23 @@ pub trait AnimationSampling: Send + Sync + 'static {
     fn current_sample(&self, channel: &Self::Channel) -> Self::Primitive;
 }
 
+/// Fill behavior for sampling queries outside `input[0]..input[last]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Boundary<T> {
+    /// Hold the nearest edge value: the first keyframe before the start, the last after the end
+    Hold,
+    /// Always return a fixed value
+    Constant(T),
+    /// Extrapolate using the slope of the nearest segment
+    Extrapolate,
+}
+
+impl<T> Default for Boundary<T> {
+    fn default() -> Self {
+        Boundary::Hold
+    }
+}
+
 /// Sampler defines a single animation for a single channel on a single component
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Sampler<T>
@@ -34,6 +53,12 @@ where
     pub output: Vec<T>,
     /// How should interpolation be done
     pub function: InterpolationFunction<T>,
+    /// Named events fired when playback crosses the given input time
+    #[serde(default)]
+    pub events: Vec<(f32, String)>,
+    /// Fill behavior used when the sampled time is out of range
+    #[serde(default)]
+    pub boundary: Boundary<T>,
 }
 
 impl<T> Asset for Sampler<T>
@@ -179,6 +204,39 @@ pub enum EndControl {
     Normal,
 }
 
+/// An event fired when animation playback crosses a named keyframe.
+#[derive(Clone, Debug)]
+pub struct AnimationEvent<T>
+where
+    T: AnimationSampling,
+{
+    /// The entity the animation is playing on
+    pub entity: Entity,
+    /// The channel the event was defined on
+    pub channel: T::Channel,
+    /// Name of the event, as given in `Sampler::events`
+    pub name: String,
+}
+
+/// Deterministic fixed-rate stepping configuration for a `SamplerControl`.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    /// Size of each discrete step, in seconds (e.g. `1.0 / 60.0`)
+    pub timestep: f32,
+    /// Leftover time not yet consumed by a whole step
+    pub accumulator: f32,
+}
+
+impl FixedTimestep {
+    /// Create a new fixed timestep with no leftover time accumulated yet
+    pub fn new(timestep: f32) -> Self {
+        FixedTimestep {
+            timestep,
+            accumulator: 0.0,
+        }
+    }
+}
+
 /// Control a single active sampler
 #[derive(Clone)]
 pub struct SamplerControl<T>
@@ -197,13 +255,66 @@ where
     pub after: T::Primitive,
     /// Control the rate of animation, default is 1.0
     pub rate_multiplier: f32,
+    /// Opt-in deterministic fixed-rate stepping, `None` advances time continuously
+    pub fixed_timestep: Option<FixedTimestep>,
+}
+
+impl<T> SamplerControl<T>
+where
+    T: AnimationSampling,
+{
+    /// Advance this control's playback time by `delta_time` seconds, respecting `fixed_timestep`.
+    ///
+    /// Returns the raw, possibly negative, new playback time rather than writing it into
+    /// `self.state` directly: `Duration` cannot hold a negative value, so callers must wrap the
+    /// returned time (e.g. via `wrap_looping_time`) before converting it back into a `Duration`.
+    fn advance(&mut self, delta_time: f32) -> Option<f32> {
+        let dur = match self.state {
+            ControlState::Running(dur) => duration_to_secs(dur),
+            _ => return None,
+        };
+        let whole = match self.fixed_timestep {
+            Some(ref mut fixed) if fixed.timestep > 0.0 => {
+                fixed.accumulator += delta_time;
+                let steps = (fixed.accumulator / fixed.timestep).floor();
+                fixed.accumulator -= steps * fixed.timestep;
+                steps * fixed.timestep
+            }
+            _ => delta_time,
+        };
+        Some(dur + whole)
+    }
+
+    /// Sample this control at its current state, interpolating in the `fixed_timestep` remainder
+    fn sample(&self, sampler: &Sampler<T::Primitive>) -> Option<T::Primitive> {
+        let dur = match self.state {
+            ControlState::Running(dur) => duration_to_secs(dur),
+            _ => return None,
+        };
+        let current = sample_at(sampler, dur);
+        match self.fixed_timestep {
+            Some(fixed) if fixed.timestep > 0.0 && fixed.accumulator > 0.0 => {
+                let looping = match self.end {
+                    EndControl::Loop(_) => true,
+                    EndControl::Normal => false,
+                };
+                let duration = *sampler.input.last().unwrap_or(&0.0);
+                let fraction = (fixed.accumulator / fixed.timestep).min(1.0);
+                let next = sample_at(
+                    sampler,
+                    wrap_looping_time(dur + fixed.timestep, duration, looping),
+                );
+                Some(current.mul(1.0 - fraction).add(&next.mul(fraction)))
+            }
+            _ => Some(current),
+        }
+    }
 }
 
 /// Sampler control set, containing a set of sampler controllers for a single component.
 ///
-/// We only support a single sampler per channel currently, i.e no animation blending. Blending is
-/// however possible to build on top of this by dynamically updating the samplers referenced from
-/// here.
+/// Only a single sampler per channel is supported here; for blending multiple animations
+/// together, see `AnimationGraph`.
 #[derive(Clone, Default)]
 pub struct SamplerControlSet<T>
 where
@@ -267,6 +378,10 @@ where
         self.samplers.values_mut().for_each(|sampler| {
             if let ControlState::Running(_) = sampler.state {
                 sampler.state = ControlState::Running(dur);
+                // A discrete jump invalidates any leftover fixed-timestep remainder.
+                if let Some(ref mut fixed) = sampler.fixed_timestep {
+                    fixed.accumulator = 0.0;
+                }
             }
         });
     }
@@ -278,6 +393,46 @@ where
             .all(|t| t.state == ControlState::Done || t.state == ControlState::Requested)
     }
 
+    /// Advance every running sampler by `delta_time` seconds (scaled by each sampler's
+    /// `rate_multiplier`), firing any events crossed into `events`.
+    pub fn update(
+        &mut self,
+        entity: Entity,
+        samplers: &AssetStorage<Sampler<T::Primitive>>,
+        delta_time: f32,
+        events: &mut EventChannel<AnimationEvent<T>>,
+    ) {
+        for (channel, control) in &mut self.samplers {
+            let sampler = match samplers.get(&control.sampler) {
+                Some(sampler) => sampler,
+                None => continue,
+            };
+            let previous = match control.state {
+                ControlState::Running(dur) => duration_to_secs(dur),
+                _ => continue,
+            };
+            let looping = match control.end {
+                EndControl::Loop(_) => true,
+                EndControl::Normal => false,
+            };
+            let duration = *sampler.input.last().unwrap_or(&0.0);
+            let current = match control.advance(delta_time * control.rate_multiplier) {
+                Some(current) => current,
+                None => continue,
+            };
+            for name in collect_events(&sampler.events, previous, current, duration, looping) {
+                events.single_write(AnimationEvent {
+                    entity,
+                    channel: channel.clone(),
+                    name,
+                });
+            }
+            control.state = ControlState::Running(secs_to_duration(wrap_looping_time(
+                current, duration, looping,
+            )));
+        }
+    }
+
     /// Step animation
     pub fn step(
         &mut self,
@@ -294,6 +449,122 @@ where
     }
 }
 
+/// Collect event names crossed between `previous` and `current`, handling loop wrap-around and
+/// reverse playback.
+///
+/// Every interval tested is half-open, inclusive on the end closest to where this frame started
+/// (`previous`) and exclusive on the end closest to where it's heading (`current`), so an event
+/// authored exactly on a frame boundary fires exactly once instead of being permanently excluded
+/// by a strict `<`/`>` on both ends — the next frame's `previous` picks up exactly where this
+/// frame's `current` left off. Both halves of a loop split use the same convention on the end
+/// they wrap through (`duration` for a forward loop, `0.0` for a reverse one). A `current` more
+/// than one `duration` past `previous` (a frame hitch, or a large accumulated `fixed_timestep`
+/// jump) fires every full lap crossed rather than only the first.
+fn collect_events(
+    sampler_events: &[(f32, String)],
+    previous: f32,
+    current: f32,
+    duration: f32,
+    looping: bool,
+) -> Vec<String> {
+    let between_inclusive_hi = |lo: f32, hi: f32| {
+        sampler_events
+            .iter()
+            .filter(move |(t, _)| *t > lo && *t <= hi)
+            .map(|(_, name)| name.clone())
+    };
+    let between_inclusive_lo = |lo: f32, hi: f32| {
+        sampler_events
+            .iter()
+            .filter(move |(t, _)| *t >= lo && *t < hi)
+            .map(|(_, name)| name.clone())
+    };
+    let full_lap = || {
+        sampler_events
+            .iter()
+            .filter(move |(t, _)| *t >= 0.0 && *t <= duration)
+            .map(|(_, name)| name.clone())
+    };
+    let mut fired = Vec::new();
+    if looping && duration > 0.0 && current >= duration {
+        fired.extend(between_inclusive_hi(previous, duration));
+        let mut remaining = current - duration;
+        while remaining >= duration {
+            fired.extend(full_lap());
+            remaining -= duration;
+        }
+        fired.extend(between_inclusive_lo(0.0, remaining));
+    } else if looping && duration > 0.0 && current <= 0.0 {
+        fired.extend(between_inclusive_lo(0.0, previous));
+        let mut remaining = -current;
+        while remaining >= duration {
+            fired.extend(full_lap());
+            remaining -= duration;
+        }
+        fired.extend(between_inclusive_hi(duration - remaining, duration));
+    } else if previous <= current {
+        fired.extend(between_inclusive_lo(previous, current));
+    } else {
+        fired.extend(between_inclusive_hi(current, previous));
+    }
+    fired
+}
+
+/// Wrap `current` back into `0.0..duration` when looping has carried it past either end.
+///
+/// A non-looping control played in reverse has nowhere further to go once it reaches the start,
+/// so `current` is clamped to `0.0` there too: `Duration` cannot hold a negative value, and
+/// leaving a negative `current` unwrapped would silently saturate to `Duration::ZERO` at the
+/// `secs_to_duration` cast instead of stopping at the start explicitly.
+fn wrap_looping_time(current: f32, duration: f32, looping: bool) -> f32 {
+    if looping && duration > 0.0 && (current >= duration || current < 0.0) {
+        current.rem_euclid(duration)
+    } else if current < 0.0 {
+        0.0
+    } else {
+        current
+    }
+}
+
+/// Advance a single control taking part in a `Transition` by `delta_time` seconds, firing any
+/// events crossed into `events` just like `SamplerControlSet::update` does.
+fn advance_transition_control<T>(
+    entity: Entity,
+    channel: &T::Channel,
+    control: &mut SamplerControl<T>,
+    samplers: &AssetStorage<Sampler<T::Primitive>>,
+    delta_time: f32,
+    events: &mut EventChannel<AnimationEvent<T>>,
+) where
+    T: AnimationSampling,
+{
+    let sampler = match samplers.get(&control.sampler) {
+        Some(sampler) => sampler,
+        None => return,
+    };
+    let previous = match control.state {
+        ControlState::Running(dur) => duration_to_secs(dur),
+        _ => return,
+    };
+    let duration = *sampler.input.last().unwrap_or(&0.0);
+    let looping = match control.end {
+        EndControl::Loop(_) => true,
+        EndControl::Normal => false,
+    };
+    if let Some(current) = control.advance(delta_time * control.rate_multiplier) {
+        for name in collect_events(&sampler.events, previous, current, duration, looping) {
+            events.single_write(AnimationEvent {
+                entity,
+                channel: channel.clone(),
+                name,
+            });
+        }
+        control.state = ControlState::Running(secs_to_duration(wrap_looping_time(
+            current, duration, looping,
+        )));
+    }
+}
+
 fn set_step_state<T>(
     control: &mut SamplerControl<T>,
     sampler: &Sampler<T::Primitive>,
@@ -313,6 +584,10 @@ fn set_step_state<T>(
             (None, _) => 0,
         };
         control.state = ControlState::Running(secs_to_duration(sampler.input[new_index]));
+        // A discrete jump invalidates any leftover fixed-timestep remainder.
+        if let Some(ref mut fixed) = control.fixed_timestep {
+            fixed.accumulator = 0.0;
+        }
     }
 }
 
@@ -337,6 +612,11 @@ pub enum StepDirection {
 pub enum AnimationCommand {
     /// Start the animation, or unpause if it's paused
     Start,
+    /// Start the animation, cross-fading out whatever is playing over `duration` seconds
+    StartWithTransition {
+        /// Length of the cross-fade, in seconds
+        duration: f32,
+    },
     /// Step the animation forward/backward (move to the next/previous input value in sequence)
     Step(StepDirection),
     /// Forcible set current interpolation point for the animation, value in seconds
@@ -347,8 +627,22 @@ pub enum AnimationCommand {
     Abort,
 }
 
+/// An in-progress cross-fade between two `SamplerControlSet`s on the same component.
+#[derive(Clone)]
+pub struct Transition<T>
+where
+    T: AnimationSampling,
+{
+    /// The sampler set that is fading out
+    pub outgoing: SamplerControlSet<T>,
+    /// Total duration of the fade, in seconds
+    pub duration: f32,
+    /// Elapsed time since the fade began, in seconds
+    pub elapsed: f32,
+}
+
 /// Controls the state of a single running animation on a specific component type
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct AnimationControl<T>
 where
     T: AnimationSampling,
@@ -363,6 +657,8 @@ where
     pub command: AnimationCommand,
     /// Control the rate of animation, default is 1.0
     pub rate_multiplier: f32,
+    /// Cross-fade out of the previous animation, if one is in progress
+    pub transition: Option<Transition<T>>,
     m: marker::PhantomData<T>,
 }
 
@@ -383,9 +679,106 @@ where
             state,
             command,
             rate_multiplier,
+            transition: None,
             m: marker::PhantomData,
         }
     }
+
+    /// Begin cross-fading out `outgoing` in favor of whatever `SamplerControlSet` is about to
+    /// replace it, over `duration` seconds.
+    pub fn start_transition(&mut self, outgoing: SamplerControlSet<T>, duration: f32) {
+        self.transition = Some(Transition {
+            outgoing,
+            duration,
+            elapsed: 0.0,
+        });
+    }
+
+    /// Advance an in-progress transition, blending outgoing/incoming samples onto `target` and
+    /// firing any events crossed by either the outgoing or incoming samplers into `events`.
+    /// Returns `true` while the fade is still in progress.
+    ///
+    /// Advances `incoming`'s samplers itself, so callers must not also run
+    /// `SamplerControlSet::update` on `incoming` for the same frame while a transition is in
+    /// progress, or it will be double-advanced.
+    pub fn step_transition(
+        &mut self,
+        entity: Entity,
+        incoming: &mut SamplerControlSet<T>,
+        samplers: &AssetStorage<Sampler<T::Primitive>>,
+        target: &mut T,
+        delta_time: f32,
+        events: &mut EventChannel<AnimationEvent<T>>,
+    ) -> bool {
+        let in_progress = {
+            let transition = match self.transition {
+                Some(ref mut transition) => transition,
+                None => return false,
+            };
+            transition.elapsed = (transition.elapsed + delta_time).min(transition.duration);
+            let weight_in = if transition.duration > 0.0 {
+                transition.elapsed / transition.duration
+            } else {
+                1.0
+            };
+            let weight_out = 1.0 - weight_in;
+
+            for (channel, control) in transition.outgoing.samplers.iter_mut() {
+                advance_transition_control(entity, channel, control, samplers, delta_time, events);
+            }
+            for (channel, control) in incoming.samplers.iter_mut() {
+                advance_transition_control(entity, channel, control, samplers, delta_time, events);
+            }
+
+            let mut channels: FnvHashMap<T::Channel, T::Primitive> = FnvHashMap::default();
+            for (channel, control) in &transition.outgoing.samplers {
+                if let Some(sampler) = samplers.get(&control.sampler) {
+                    if let Some(sample) = control.sample(sampler) {
+                        channels.insert(channel.clone(), sample.mul(weight_out));
+                    }
+                }
+            }
+            for (channel, control) in &incoming.samplers {
+                if let Some(sampler) = samplers.get(&control.sampler) {
+                    if let Some(sample) = control.sample(sampler) {
+                        let scaled = sample.mul(weight_in);
+                        channels
+                            .entry(channel.clone())
+                            .and_modify(|acc| *acc = acc.add(&scaled))
+                            .or_insert(scaled);
+                    }
+                }
+            }
+            for (channel, sample) in &channels {
+                target.apply_sample(channel, sample);
+            }
+
+            transition.elapsed < transition.duration
+        };
+
+        if !in_progress {
+            self.transition = None;
+        }
+        in_progress
+    }
+}
+
+impl<T> fmt::Debug for AnimationControl<T>
+where
+    T: AnimationSampling,
+{
+    /// `Transition` wraps a `SamplerControlSet`, which never implements `Debug`, so it's reported
+    /// here as just whether a fade is in progress.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AnimationControl")
+            .field("animation", &self.animation)
+            .field("end", &self.end)
+            .field("state", &self.state)
+            .field("command", &self.command)
+            .field("rate_multiplier", &self.rate_multiplier)
+            .field("transition", &self.transition.is_some())
+            .finish()
+    }
 }
 
 impl<T> Component for AnimationControl<T>
@@ -410,3 +803,684 @@ where
 {
     type Storage = DenseVecStorage<Self>;
 }
+
+/// Sample a `Sampler` at the given input value, interpolating or falling back to `Boundary`.
+fn sample_at<T>(sampler: &Sampler<T>, time: f32) -> T
+where
+    T: InterpolationPrimitive + Clone,
+{
+    match (sampler.input.first(), sampler.input.last()) {
+        (Some(&first), _) if time < first => boundary_sample(sampler, time, true),
+        (_, Some(&last)) if time > last => boundary_sample(sampler, time, false),
+        (Some(_), Some(_)) => sampler
+            .function
+            .interpolate(time, &sampler.input, &sampler.output),
+        _ => boundary_sample(sampler, time, true),
+    }
+}
+
+/// Fill a sample for `time` outside the sampler's keyframe range, per `Sampler::boundary`.
+fn boundary_sample<T>(sampler: &Sampler<T>, time: f32, before: bool) -> T
+where
+    T: InterpolationPrimitive + Clone,
+{
+    match sampler.boundary {
+        Boundary::Constant(ref value) => value.clone(),
+        Boundary::Hold | Boundary::Extrapolate if sampler.input.len() < 2 => {
+            let edge = *sampler.input.first().unwrap_or(&0.0);
+            sampler
+                .function
+                .interpolate(edge, &sampler.input, &sampler.output)
+        }
+        Boundary::Hold => {
+            let edge = if before {
+                sampler.input[0]
+            } else {
+                sampler.input[sampler.input.len() - 1]
+            };
+            sampler
+                .function
+                .interpolate(edge, &sampler.input, &sampler.output)
+        }
+        Boundary::Extrapolate => {
+            let (t0, t1) = if before {
+                (sampler.input[0], sampler.input[1])
+            } else {
+                let len = sampler.input.len();
+                (sampler.input[len - 2], sampler.input[len - 1])
+            };
+            // A zero-length edge segment (two keyframes sharing an input time, e.g. an
+            // authored instantaneous snap) has no slope to extrapolate; fall back to Hold.
+            if t1 - t0 == 0.0 {
+                let edge = if before { t0 } else { t1 };
+                return sampler
+                    .function
+                    .interpolate(edge, &sampler.input, &sampler.output);
+            }
+            let v0 = sampler
+                .function
+                .interpolate(t0, &sampler.input, &sampler.output);
+            let v1 = sampler
+                .function
+                .interpolate(t1, &sampler.input, &sampler.output);
+            let slope = v1.sub(&v0).mul(1.0 / (t1 - t0));
+            let (base_t, base_v) = if before { (t0, v0) } else { (t1, v1) };
+            base_v.add(&slope.mul(time - base_t))
+        }
+    }
+}
+
+/// A single node in an `AnimationGraph`.
+#[derive(Clone, Debug)]
+pub enum AnimationGraphNode<T>
+where
+    T: AnimationSampling,
+{
+    /// A leaf node, sampling a single clip at a given weight relative to its siblings.
+    Clip {
+        /// The clip to sample
+        animation: Handle<Animation<T>>,
+        /// Current playback state of the clip
+        state: ControlState,
+        /// What to do when the clip reaches the end of its duration
+        end: EndControl,
+        /// Rate of playback, default is 1.0
+        rate_multiplier: f32,
+        /// Weight of this clip relative to its siblings under the same parent
+        weight: f32,
+    },
+    /// An interior node, scaling the weight of all its descendants.
+    Blend {
+        /// Children of this node, indices into `AnimationGraph::nodes`
+        children: Vec<usize>,
+        /// Weight of this node relative to its siblings under the same parent
+        weight: f32,
+    },
+}
+
+impl<T> AnimationGraphNode<T>
+where
+    T: AnimationSampling,
+{
+    fn weight(&self) -> f32 {
+        match *self {
+            AnimationGraphNode::Clip { weight, .. } => weight,
+            AnimationGraphNode::Blend { weight, .. } => weight,
+        }
+    }
+}
+
+/// A directed acyclic graph of weighted animation clips, blended together bottom-up from `root`.
+#[derive(Clone, Debug)]
+pub struct AnimationGraph<T>
+where
+    T: AnimationSampling,
+{
+    /// All nodes in the graph
+    pub nodes: Vec<AnimationGraphNode<T>>,
+    /// Index into `nodes` of the root node
+    pub root: usize,
+}
+
+impl<T> AnimationGraph<T>
+where
+    T: AnimationSampling,
+{
+    /// Create a new, empty graph. `root` must be set to a valid node index before evaluating.
+    pub fn new() -> Self {
+        AnimationGraph {
+            nodes: Vec::new(),
+            root: 0,
+        }
+    }
+
+    /// Advance every `Clip` node's own playback clock by `delta_time` seconds.
+    pub fn advance(
+        &mut self,
+        delta_time: f32,
+        animations: &AssetStorage<Animation<T>>,
+        samplers: &AssetStorage<Sampler<T::Primitive>>,
+    ) {
+        for node in &mut self.nodes {
+            let (animation, state, end, rate_multiplier) = match *node {
+                AnimationGraphNode::Clip {
+                    ref animation,
+                    ref mut state,
+                    ref end,
+                    rate_multiplier,
+                    ..
+                } => (animation, state, end, rate_multiplier),
+                AnimationGraphNode::Blend { .. } => continue,
+            };
+            let dur = match *state {
+                ControlState::Running(dur) => duration_to_secs(dur),
+                _ => continue,
+            };
+            let animation = match animations.get(animation) {
+                Some(animation) => animation,
+                None => continue,
+            };
+            let duration = animation_duration(animation, samplers);
+            let looping = match *end {
+                EndControl::Loop(_) => true,
+                EndControl::Normal => false,
+            };
+            let current = wrap_looping_time(dur + delta_time * rate_multiplier, duration, looping);
+            *state = ControlState::Running(secs_to_duration(current));
+        }
+    }
+
+    /// Evaluate the graph and apply the blended result to `target`.
+    ///
+    /// `nodes` is meant to form a DAG rooted at `root`, but nothing short of this traversal
+    /// enforces that; an out-of-range `root`/child index or a cycle accidentally introduced by
+    /// hand-built graph data is skipped rather than panicking or recursing forever.
+    pub fn evaluate(
+        &self,
+        animations: &AssetStorage<Animation<T>>,
+        samplers: &AssetStorage<Sampler<T::Primitive>>,
+        target: &mut T,
+    ) {
+        if self.nodes.is_empty() || self.root >= self.nodes.len() {
+            return;
+        }
+        let mut channels: FnvHashMap<T::Channel, T::Primitive> = FnvHashMap::default();
+        let mut on_path = vec![false; self.nodes.len()];
+        self.accumulate(
+            self.root,
+            1.0,
+            animations,
+            samplers,
+            &mut channels,
+            &mut on_path,
+        );
+        for (channel, sample) in &channels {
+            target.apply_sample(channel, sample);
+        }
+    }
+
+    /// Accumulate weighted samples from `index` and its descendants into `out`.
+    ///
+    /// `on_path` tracks nodes on the current root-to-node path so a `children` entry that loops
+    /// back to an ancestor (breaking the DAG invariant) is skipped instead of recursing forever.
+    fn accumulate(
+        &self,
+        index: usize,
+        weight: f32,
+        animations: &AssetStorage<Animation<T>>,
+        samplers: &AssetStorage<Sampler<T::Primitive>>,
+        out: &mut FnvHashMap<T::Channel, T::Primitive>,
+        on_path: &mut [bool],
+    ) {
+        if weight <= 0.0 || index >= self.nodes.len() || on_path[index] {
+            return;
+        }
+        on_path[index] = true;
+        match self.nodes[index] {
+            AnimationGraphNode::Clip {
+                ref animation,
+                ref state,
+                ..
+            } => {
+                if let ControlState::Running(dur) = *state {
+                    let time = duration_to_secs(dur);
+                    if let Some(animation) = animations.get(animation) {
+                        for &(_, ref channel, ref sampler_handle) in &animation.nodes {
+                            if let Some(sampler) = samplers.get(sampler_handle) {
+                                let scaled = sample_at(sampler, time).mul(weight);
+                                out.entry(channel.clone())
+                                    .and_modify(|acc| *acc = acc.add(&scaled))
+                                    .or_insert(scaled);
+                            }
+                        }
+                    }
+                }
+            }
+            AnimationGraphNode::Blend { ref children, .. } => {
+                let valid_children: Vec<usize> = children
+                    .iter()
+                    .cloned()
+                    .filter(|&c| c < self.nodes.len())
+                    .collect();
+                let total: f32 = valid_children.iter().map(|&c| self.nodes[c].weight()).sum();
+                if total > 0.0 {
+                    for &child in &valid_children {
+                        let child_weight = self.nodes[child].weight() / total;
+                        self.accumulate(
+                            child,
+                            weight * child_weight,
+                            animations,
+                            samplers,
+                            out,
+                            on_path,
+                        );
+                    }
+                }
+            }
+        }
+        on_path[index] = false;
+    }
+}
+
+/// The length of an animation, in seconds, taken as the longest of its channels' samplers.
+fn animation_duration<T>(
+    animation: &Animation<T>,
+    samplers: &AssetStorage<Sampler<T::Primitive>>,
+) -> f32
+where
+    T: AnimationSampling,
+{
+    animation
+        .nodes
+        .iter()
+        .filter_map(|&(_, _, ref sampler_handle)| samplers.get(sampler_handle))
+        .filter_map(|sampler| sampler.input.last().cloned())
+        .fold(0.0, f32::max)
+}
+
+impl<T> Component for AnimationGraph<T>
+where
+    T: AnimationSampling,
+{
+    type Storage = DenseVecStorage<Self>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use amethyst_assets::AssetStorage;
+    use amethyst_core::timing::secs_to_duration;
+    use minterpolate::InterpolationFunction;
+
+    use shrev::EventChannel;
+    use specs::World;
+
+    use super::{
+        collect_events, wrap_looping_time, Animation, AnimationCommand, AnimationControl,
+        AnimationGraph, AnimationGraphNode, AnimationSampling, Boundary, ControlState, EndControl,
+        FixedTimestep, Sampler, SamplerControl, SamplerControlSet,
+    };
+
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct TestTarget {
+        value: f32,
+    }
+
+    impl AnimationSampling for TestTarget {
+        type Primitive = f32;
+        type Channel = ();
+
+        fn apply_sample(&mut self, _channel: &(), data: &f32) {
+            self.value = *data;
+        }
+
+        fn current_sample(&self, _channel: &()) -> f32 {
+            self.value
+        }
+    }
+
+    fn linear_sampler(input: Vec<f32>, output: Vec<f32>) -> Sampler<f32> {
+        Sampler {
+            input,
+            output,
+            function: InterpolationFunction::Linear,
+            events: Vec::new(),
+            boundary: Boundary::default(),
+        }
+    }
+
+    fn test_control(
+        state: ControlState,
+        end: EndControl,
+        fixed_timestep: Option<FixedTimestep>,
+        storage: &mut AssetStorage<Sampler<f32>>,
+        sampler: Sampler<f32>,
+    ) -> SamplerControl<TestTarget> {
+        SamplerControl {
+            channel: (),
+            sampler: storage.insert(sampler),
+            state,
+            end,
+            after: 0.0,
+            rate_multiplier: 1.0,
+            fixed_timestep,
+        }
+    }
+
+    #[test]
+    fn advance_accumulates_fixed_timestep_across_sub_timestep_frames() {
+        let mut storage = AssetStorage::new();
+        let mut control = test_control(
+            ControlState::Running(Duration::from_secs(0)),
+            EndControl::Normal,
+            Some(FixedTimestep::new(0.25)),
+            &mut storage,
+            linear_sampler(vec![0.0, 1.0], vec![0.0, 8.0]),
+        );
+
+        // First sub-timestep frame (0.125s) doesn't reach a whole 0.25s step yet, so the raw
+        // playback time doesn't move and the remainder is carried in the accumulator.
+        assert_eq!(control.advance(0.125), Some(0.0));
+        control.state = ControlState::Running(secs_to_duration(0.0));
+
+        // The second frame's remainder completes the step, advancing by exactly one timestep.
+        assert_eq!(control.advance(0.125), Some(0.25));
+    }
+
+    #[test]
+    fn advance_accumulates_negative_steps_for_reverse_playback() {
+        let mut storage = AssetStorage::new();
+        let mut control = test_control(
+            ControlState::Running(secs_to_duration(0.125)),
+            EndControl::Loop(None),
+            Some(FixedTimestep::new(0.25)),
+            &mut storage,
+            linear_sampler(vec![0.0, 1.0], vec![0.0, 8.0]),
+        );
+
+        // A negative delta_time (as produced by a negative rate_multiplier) near the loop
+        // boundary at 0.0 should step backwards by a whole negative timestep and produce a raw
+        // negative time for the caller to wrap, rather than stalling at zero.
+        let raw = control.advance(-0.125).expect("control is running");
+        assert_eq!(raw, -0.125);
+        assert_eq!(wrap_looping_time(raw, 1.0, true), 0.875);
+    }
+
+    #[test]
+    fn sample_interpolates_fixed_timestep_remainder_across_a_loop_seam() {
+        let mut storage = AssetStorage::new();
+        let control = test_control(
+            ControlState::Running(secs_to_duration(0.875)),
+            EndControl::Loop(None),
+            Some(FixedTimestep {
+                timestep: 0.25,
+                accumulator: 0.125,
+            }),
+            &mut storage,
+            linear_sampler(vec![0.0, 1.0], vec![0.0, 8.0]),
+        );
+        let sampler = storage.get(&control.sampler).unwrap();
+
+        // The probe time (0.875 + 0.25 = 1.125) overshoots the sampler's last keyframe and must
+        // be wrapped back to 0.125 before sampling, so the interpolated result blends towards the
+        // start of the next lap (value 1.0) instead of freezing on the held end value (8.0).
+        assert_eq!(control.sample(sampler), Some(4.0));
+    }
+
+    fn clip_node(
+        animation_storage: &mut AssetStorage<Animation<TestTarget>>,
+        sampler_storage: &mut AssetStorage<Sampler<f32>>,
+        value: f32,
+        weight: f32,
+    ) -> AnimationGraphNode<TestTarget> {
+        let sampler_handle =
+            sampler_storage.insert(linear_sampler(vec![0.0, 1.0], vec![value, value]));
+        let animation_handle = animation_storage.insert(Animation {
+            nodes: vec![(0, (), sampler_handle)],
+        });
+        AnimationGraphNode::Clip {
+            animation: animation_handle,
+            state: ControlState::Running(Duration::from_secs(0)),
+            end: EndControl::Normal,
+            rate_multiplier: 1.0,
+            weight,
+        }
+    }
+
+    #[test]
+    fn accumulate_normalizes_sibling_weights() {
+        let mut animation_storage = AssetStorage::new();
+        let mut sampler_storage = AssetStorage::new();
+        let nodes = vec![
+            clip_node(&mut animation_storage, &mut sampler_storage, 2.0, 1.0),
+            clip_node(&mut animation_storage, &mut sampler_storage, 10.0, 3.0),
+            AnimationGraphNode::Blend {
+                children: vec![0, 1],
+                weight: 1.0,
+            },
+        ];
+        let graph = AnimationGraph { nodes, root: 2 };
+        let mut target = TestTarget::default();
+
+        graph.evaluate(&animation_storage, &sampler_storage, &mut target);
+
+        // Siblings are normalized to sum to 1.0 (weights 1.0 and 3.0 become 0.25 and 0.75), so
+        // the blended result is 2.0 * 0.25 + 10.0 * 0.75 = 8.0, not the raw weighted sum 32.0.
+        assert_eq!(target.value, 8.0);
+    }
+
+    #[test]
+    fn accumulate_skips_zero_weight_subtree() {
+        let mut animation_storage = AssetStorage::new();
+        let mut sampler_storage = AssetStorage::new();
+        let nodes = vec![
+            clip_node(&mut animation_storage, &mut sampler_storage, 5.0, 1.0),
+            clip_node(&mut animation_storage, &mut sampler_storage, 1000.0, 0.0),
+            AnimationGraphNode::Blend {
+                children: vec![0, 1],
+                weight: 1.0,
+            },
+        ];
+        let graph = AnimationGraph { nodes, root: 2 };
+        let mut target = TestTarget::default();
+
+        graph.evaluate(&animation_storage, &sampler_storage, &mut target);
+
+        // The zero-weight sibling contributes nothing, so only the first clip's value survives.
+        assert_eq!(target.value, 5.0);
+    }
+
+    #[test]
+    fn accumulate_ignores_out_of_range_child_index() {
+        let mut animation_storage = AssetStorage::new();
+        let mut sampler_storage = AssetStorage::new();
+        let nodes = vec![
+            clip_node(&mut animation_storage, &mut sampler_storage, 6.0, 1.0),
+            AnimationGraphNode::Blend {
+                children: vec![0, 99],
+                weight: 1.0,
+            },
+        ];
+        let graph = AnimationGraph { nodes, root: 1 };
+        let mut target = TestTarget::default();
+
+        // Index 99 is out of range; it must be skipped rather than panicking.
+        graph.evaluate(&animation_storage, &sampler_storage, &mut target);
+
+        assert_eq!(target.value, 6.0);
+    }
+
+    #[test]
+    fn accumulate_does_not_recurse_forever_on_a_cyclic_child() {
+        let animation_storage = AssetStorage::new();
+        let sampler_storage = AssetStorage::new();
+        let nodes = vec![AnimationGraphNode::Blend {
+            children: vec![0],
+            weight: 1.0,
+        }];
+        let graph = AnimationGraph { nodes, root: 0 };
+        let mut target = TestTarget::default();
+
+        // The blend node lists itself as its own child; `on_path` must stop the second visit
+        // rather than recursing forever.
+        graph.evaluate(&animation_storage, &sampler_storage, &mut target);
+
+        assert_eq!(target.value, 0.0);
+    }
+
+    fn running_set(
+        sampler_storage: &mut AssetStorage<Sampler<f32>>,
+        value: f32,
+    ) -> SamplerControlSet<TestTarget> {
+        let mut set = SamplerControlSet::default();
+        set.set_channel(
+            (),
+            SamplerControl {
+                channel: (),
+                sampler: sampler_storage.insert(linear_sampler(vec![0.0, 1.0], vec![value, value])),
+                state: ControlState::Running(Duration::from_secs(0)),
+                end: EndControl::Normal,
+                after: value,
+                rate_multiplier: 1.0,
+                fixed_timestep: None,
+            },
+        );
+        set
+    }
+
+    #[test]
+    fn step_transition_blends_outgoing_and_incoming_then_ends() {
+        let mut animation_storage = AssetStorage::new();
+        let mut sampler_storage = AssetStorage::new();
+        let animation_handle = animation_storage.insert(Animation { nodes: Vec::new() });
+        let world = World::new();
+        let entity = world.create_entity().build();
+        let mut event_channel = EventChannel::new();
+
+        let mut control = AnimationControl::new(
+            animation_handle,
+            EndControl::Normal,
+            ControlState::Running(Duration::from_secs(0)),
+            AnimationCommand::Start,
+            1.0,
+        );
+        control.start_transition(running_set(&mut sampler_storage, 0.0), 1.0);
+        let mut incoming = running_set(&mut sampler_storage, 10.0);
+        let mut target = TestTarget::default();
+
+        // Halfway through the fade, the blend is an even mix of the outgoing (0.0) and incoming
+        // (10.0) samples, and the fade is still in progress.
+        let in_progress = control.step_transition(
+            entity,
+            &mut incoming,
+            &sampler_storage,
+            &mut target,
+            0.5,
+            &mut event_channel,
+        );
+        assert!(in_progress);
+        assert_eq!(target.value, 5.0);
+
+        // The remaining half of the fade completes it: the incoming sample fully replaces the
+        // outgoing one, and the transition reports itself finished.
+        let in_progress = control.step_transition(
+            entity,
+            &mut incoming,
+            &sampler_storage,
+            &mut target,
+            0.5,
+            &mut event_channel,
+        );
+        assert!(!in_progress);
+        assert_eq!(target.value, 10.0);
+        assert!(control.transition.is_none());
+    }
+
+    #[test]
+    fn boundary_sample_extrapolate_falls_back_to_hold_on_a_zero_length_edge_segment() {
+        // Two keyframes sharing an input time (e.g. an authored instantaneous snap) have no slope
+        // to extrapolate; sampling outside the range must fall back to a finite Hold-equivalent
+        // value instead of dividing by a zero-length segment and propagating NaN/inf.
+        let mut sampler = linear_sampler(vec![0.0, 0.0, 1.0], vec![0.0, 0.0, 5.0]);
+        sampler.boundary = Boundary::Extrapolate;
+
+        let sample = super::sample_at(&sampler, -0.5);
+        assert!(sample.is_finite());
+        assert_eq!(sample, 0.0);
+    }
+
+    fn events(names: &[&str]) -> Vec<(f32, String)> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (i as f32, (*name).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn collect_events_forward() {
+        let evts = events(&["a", "b", "c"]);
+        assert_eq!(
+            collect_events(&evts, 0.5, 1.5, 2.0, false),
+            vec!["b".to_string()]
+        );
+    }
+
+    #[test]
+    fn collect_events_forward_start_fires_on_first_frame() {
+        let evts = events(&["start"]);
+        assert_eq!(
+            collect_events(&evts, 0.0, 0.1, 1.0, false),
+            vec!["start".to_string()]
+        );
+    }
+
+    #[test]
+    fn collect_events_forward_does_not_refire_on_shared_frame_boundary() {
+        let evts = vec![(1.0, "mid".to_string())];
+        // "mid" sits at t = 1.0, exactly the boundary between two consecutive frames: it must
+        // fire for the frame it's the (inclusive) start of, and not again for the frame it's the
+        // (exclusive) end of, so it fires exactly once across the pair.
+        assert_eq!(
+            collect_events(&evts, 0.0, 1.0, 3.0, false),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            collect_events(&evts, 1.0, 2.0, 3.0, false),
+            vec!["mid".to_string()]
+        );
+    }
+
+    #[test]
+    fn collect_events_reverse() {
+        let evts = events(&["a", "b", "c"]);
+        assert_eq!(
+            collect_events(&evts, 1.5, 0.5, 2.0, false),
+            vec!["b".to_string()]
+        );
+    }
+
+    #[test]
+    fn collect_events_loop_exact_boundary_fires_once() {
+        let evts = vec![(2.0, "end".to_string())];
+        // Landing exactly on the loop point should fire "end" once, not be swallowed by the wrap.
+        assert_eq!(
+            collect_events(&evts, 1.5, 2.0, 2.0, true),
+            vec!["end".to_string()]
+        );
+    }
+
+    #[test]
+    fn collect_events_loop_wraps_forward() {
+        let evts = vec![(0.0, "start".to_string()), (1.5, "mid".to_string())];
+        // previous = 1.8, current = 2.2 wraps past duration 2.0 back around to 0.2.
+        let mut fired = collect_events(&evts, 1.8, 2.2, 2.0, true);
+        fired.sort();
+        assert_eq!(fired, vec!["start".to_string()]);
+    }
+
+    #[test]
+    fn collect_events_multi_lap_hitch_fires_every_lap() {
+        let evts = vec![(0.5, "tick".to_string())];
+        // A single frame hitch that spans more than two full laps should fire "tick" once per
+        // lap crossed, not just once.
+        let fired = collect_events(&evts, 0.0, 6.5, 2.0, true);
+        assert_eq!(fired, vec!["tick", "tick", "tick"]);
+    }
+
+    #[test]
+    fn wrap_looping_time_forward_overflow() {
+        assert_eq!(wrap_looping_time(2.5, 2.0, true), 0.5);
+    }
+
+    #[test]
+    fn wrap_looping_time_reverse_underflow() {
+        assert_eq!(wrap_looping_time(-0.5, 2.0, true), 1.5);
+    }
+
+    #[test]
+    fn wrap_looping_time_non_looping_clamps_at_zero() {
+        assert_eq!(wrap_looping_time(-0.5, 2.0, false), 0.0);
+    }
+}